@@ -12,50 +12,169 @@ use glium::texture::TextureCreationError;
 use glium::texture::RawImage2d;
 use std::borrow::Cow;
 use glium::texture::ClientFormat;
+use glium::Rect;
 use crate::atlas::Atlas;
+use std::collections::HashMap;
+use std::collections::VecDeque;
 
 
+/// sRGB's conventional gamma, for converting a `fill` color from sRGB to linear space
+/// before blending. See `SolidTextProgram::draw`.
+pub const DEFAULT_GAMMA: f32 = 2.2;
+
 pub struct SolidTextProgram {
     pub program: Program,
+    pub styled_program: Program,
+    /// Samples a 3-channel MSDF atlas, reconstructing coverage as the median of the three
+    /// channels so sharp corners survive. See `draw_msdf`.
+    pub msdf_program: Program,
+    /// Branches per vertex between the distance field and a second, directly-sampled icon
+    /// atlas. See `draw_with_icons`.
+    pub icon_program: Program,
+}
+
+/// Parameters for `SolidTextProgram::draw_styled`, exploiting the distance field already
+/// sampled in the fragment shader to add an outline, a soft glow, and a drop shadow
+/// without any extra geometry or draw calls.
+#[derive(Copy, Clone)]
+pub struct TextStyle {
+    pub outline_color: (f32, f32, f32, f32),
+    pub outline_width: f32,
+    pub glow_color: (f32, f32, f32, f32),
+    /// How far the soft glow's smoothstep band extends past the glyph edge, in the same
+    /// units as `distance`. Larger values produce a wider, softer halo.
+    pub glow_radius: f32,
+    pub shadow_color: (f32, f32, f32, f32),
+    pub shadow_offset: (f32, f32),
+    pub shadow_softness: f32,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            outline_color: (0.0, 0.0, 0.0, 0.0),
+            outline_width: 0.0,
+            glow_color: (0.0, 0.0, 0.0, 0.0),
+            glow_radius: 0.0,
+            shadow_color: (0.0, 0.0, 0.0, 0.0),
+            shadow_offset: (0.0, 0.0),
+            shadow_softness: 0.0,
+        }
+    }
 }
 
 #[derive(Copy, Clone)]
 pub struct GlyphQuadVertex {
     position: (f32, f32),
     texture_coordinate: (f32, f32),
+    /// `1.0` if `texture_coordinate` indexes the icon atlas rather than the font's
+    /// distance field; see `CustomGlyph`. Always `0.0` outside of `TextMesh::new_with_custom`.
+    is_custom: f32,
 }
 
 pub struct TextMesh {
     vertices: glium::VertexBuffer<GlyphQuadVertex>,
     indices: glium::IndexBuffer<u16>,
-    width: f32,
+    metrics: TextMetrics,
+}
+
+/// Horizontal alignment of each line within a multi-line `TextMesh`, applied once a
+/// line's extent is known.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// Controls how `TextMesh::compute_buffers` lays out multiple lines: where to wrap,
+/// how far apart lines sit, and how each line is aligned.
+#[derive(Copy, Clone, Debug)]
+pub struct LayoutOptions {
+    /// Wrap a line before a word would cross this width, in mesh units. `None` disables
+    /// word wrapping; lines only break on `\n`/`\r`.
+    pub max_width: Option<f32>,
+    /// Vertical distance between the pen positions of consecutive lines, usually the
+    /// font's `ascent - descent + line_gap`.
+    pub line_height: f32,
+    pub align: Align,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        LayoutOptions { max_width: None, line_height: 1.0, align: Align::Left }
+    }
+}
+
+/// The bounding box of a laid-out `TextMesh`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TextMetrics {
+    pub width: f32,
+    pub height: f32,
+    pub line_count: usize,
+}
+
+/// A non-font image (icon, emoji, UI symbol) that flows with surrounding text, reserving
+/// horizontal advance like a glyph. `id` identifies its rectangle in the icon atlas texture
+/// passed to `SolidTextProgram::draw_with_icons`.
+#[derive(Copy, Clone, Debug)]
+pub struct CustomGlyph {
+    pub id: u32,
+    pub width: f32,
+    pub height: f32,
+    /// Offset from the text baseline to the glyph's bottom edge, mirroring a font glyph's
+    /// descent so icons sit level with surrounding letters.
+    pub baseline_offset: f32,
+}
+
+/// One run of a mixed text+icon layout passed to `TextMesh::new_with_custom`: either a
+/// span of ordinary text laid out by `font`, or a single custom glyph.
+pub enum TextSpan<'a> {
+    Text(&'a str),
+    Custom(CustomGlyph),
 }
 
 #[derive(Debug)]
 pub enum TextMeshCreationError {
     Vertex(glium::vertex::BufferCreationError),
     Index(glium::index::BufferCreationError),
+    Cache(GlyphCacheError),
 }
 
 
-glium::implement_vertex!(GlyphQuadVertex, position, texture_coordinate);
+glium::implement_vertex!(GlyphQuadVertex, position, texture_coordinate, is_custom);
 
 
+/// How many channels an atlas's distance field packs per texel. A single channel stores
+/// one scalar distance, which rounds off sharp corners where two edges meet at a point
+/// (serifs, box-drawing, small UI glyphs). `Msdf` stores three independently-encoded edge
+/// distances so the fragment shader can reconstruct a sharp corner as their median.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AtlasChannels {
+    SingleChannel,
+    Msdf,
+}
+
 pub fn atlas_texture(facade: &impl Facade, atlas: &Atlas)
                      -> Result<Texture2d, TextureCreationError>
 {
-    raw_u8_texture(facade, &atlas.distance_field, atlas.resolution)
+    raw_u8_texture(facade, &atlas.distance_field, atlas.resolution, atlas.channels)
 }
 
-pub fn raw_u8_texture(facade: &impl Facade, atlas: &[u8], dimensions: (usize, usize))
+pub fn raw_u8_texture(facade: &impl Facade, atlas: &[u8], dimensions: (usize, usize), channels: AtlasChannels)
                       -> Result<Texture2d, TextureCreationError>
 {
+    let format = match channels {
+        AtlasChannels::SingleChannel => ClientFormat::U8,
+        AtlasChannels::Msdf => ClientFormat::U8U8U8,
+    };
+
     glium::texture::Texture2d::new(
         facade, RawImage2d {
             data: Cow::Borrowed(atlas),
             width: dimensions.0 as u32,
             height: dimensions.1 as u32,
-            format: ClientFormat::U8
+            format
         }
     )
 }
@@ -84,27 +203,200 @@ impl SolidTextProgram {
 
                 uniform vec4 fill;
                 uniform texture2d distance_field;
+                uniform float pxrange;
+                uniform float gamma;
+
+                void main(){
+                    float distance = texture(distance_field, texture_position).r;
+
+                    float w = clamp(fwidth(distance) * pxrange, 0.0, 0.5);
+                    float alpha = smoothstep(0.5 - w, 0.5 + w, distance);
+
+                    vec4 linear_fill = vec4(pow(fill.rgb, vec3(gamma)), fill.a);
+                    color = linear_fill * vec4(vec3(1.0), alpha);
+                }
+            "#,
+
+            None
+        )?;
+
+        let styled_program = glium::Program::from_source(
+            facade,
+
+            r#"#version 330
+                in vec2 position;
+                in vec2 texture_coordinate;
+                out vec2 texture_position;
+
+                uniform mat4 transform;
+
+                void main(){
+                    gl_Position = (transform * vec4(position, 1.0, 1.0));
+                    texture_position = texture_coordinate;
+                }
+            "#,
+
+            r#"#version 330
+                in vec2 texture_position;
+                out vec4 color;
+
+                uniform vec4 fill;
+                uniform sampler2D distance_field;
+                uniform float pxrange;
+                uniform float gamma;
+
+                uniform vec4 outline_color;
+                uniform float outline_width;
+                uniform vec4 glow_color;
+                uniform float glow_radius;
+                uniform vec4 shadow_color;
+                uniform vec2 shadow_offset;
+                uniform float shadow_softness;
+
+                vec4 linearize(vec4 c) {
+                    return vec4(pow(c.rgb, vec3(gamma)), c.a);
+                }
 
                 void main(){
                     float distance = texture(distance_field, texture_position).r;
-                    distance = distance > 0.5? 1.0 : 0.0; // TODO
+                    float w = clamp(fwidth(distance) * pxrange, 0.0, 0.5);
+
+                    vec4 linear_fill = linearize(fill);
+                    vec4 linear_outline = linearize(outline_color);
+                    vec4 linear_glow = linearize(glow_color);
+                    vec4 linear_shadow = linearize(shadow_color);
+
+                    float fill_alpha = smoothstep(0.5 - w, 0.5 + w, distance);
+                    float outline_alpha = smoothstep(0.5 - outline_width - w, 0.5 - outline_width + w, distance);
+
+                    vec4 premultiplied_fill = vec4(linear_fill.rgb * linear_fill.a * fill_alpha, linear_fill.a * fill_alpha);
+                    vec4 premultiplied_outline = vec4(linear_outline.rgb * linear_outline.a * outline_alpha, linear_outline.a * outline_alpha);
+                    vec4 glyph = premultiplied_outline * (1.0 - premultiplied_fill.a) + premultiplied_fill;
+
+                    float glow_alpha = smoothstep(0.5 - glow_radius, 0.5 + glow_radius, distance);
+                    vec4 glow = vec4(linear_glow.rgb * linear_glow.a * glow_alpha, linear_glow.a * glow_alpha);
+
+                    float d_shadow = texture(distance_field, texture_position - shadow_offset).r;
+                    float shadow_alpha = smoothstep(0.5 - shadow_softness, 0.5 + shadow_softness, d_shadow);
+                    vec4 shadow = vec4(linear_shadow.rgb * linear_shadow.a * shadow_alpha, linear_shadow.a * shadow_alpha);
+
+                    vec4 under_glyph = shadow * (1.0 - glow.a) + glow;
+                    color = under_glyph * (1.0 - glyph.a) + glyph;
+                }
+            "#,
+
+            None
+        )?;
+
+        let msdf_program = glium::Program::from_source(
+            facade,
+
+            r#"#version 330
+                in vec2 position;
+                in vec2 texture_coordinate;
+                out vec2 texture_position;
+
+                uniform mat4 transform;
+
+                void main(){
+                    gl_Position = (transform * vec4(position, 1.0, 1.0));
+                    texture_position = texture_coordinate;
+                }
+            "#,
+
+            r#"#version 330
+                in vec2 texture_position;
+                out vec4 color;
+
+                uniform vec4 fill;
+                uniform sampler2D distance_field;
+                uniform float pxrange;
+                uniform float gamma;
+
+                float median(float a, float b, float c) {
+                    return max(min(a, b), min(max(a, b), c));
+                }
+
+                void main(){
+                    vec3 s = texture(distance_field, texture_position).rgb;
+                    float distance = median(s.r, s.g, s.b);
+
+                    float w = clamp(fwidth(distance) * pxrange, 0.0, 0.5);
+                    float alpha = smoothstep(0.5 - w, 0.5 + w, distance);
+
+                    vec4 linear_fill = vec4(pow(fill.rgb, vec3(gamma)), fill.a);
+                    color = linear_fill * vec4(vec3(1.0), alpha);
+                }
+            "#,
+
+            None
+        )?;
+
+        let icon_program = glium::Program::from_source(
+            facade,
+
+            r#"#version 330
+                in vec2 position;
+                in vec2 texture_coordinate;
+                in float is_custom;
+                out vec2 texture_position;
+                flat out float vertex_is_custom;
+
+                uniform mat4 transform;
 
-                    color = fill * vec4(vec3(1.0), distance);
+                void main(){
+                    gl_Position = (transform * vec4(position, 1.0, 1.0));
+                    texture_position = texture_coordinate;
+                    vertex_is_custom = is_custom;
+                }
+            "#,
+
+            r#"#version 330
+                in vec2 texture_position;
+                flat in float vertex_is_custom;
+                out vec4 color;
+
+                uniform vec4 fill;
+                uniform sampler2D distance_field;
+                uniform sampler2D icon_atlas;
+                uniform float pxrange;
+                uniform float gamma;
+
+                void main(){
+                    if (vertex_is_custom > 0.5) {
+                        color = texture(icon_atlas, texture_position);
+                    } else {
+                        float distance = texture(distance_field, texture_position).r;
+                        float w = clamp(fwidth(distance) * pxrange, 0.0, 0.5);
+                        float alpha = smoothstep(0.5 - w, 0.5 + w, distance);
+
+                        vec4 linear_fill = vec4(pow(fill.rgb, vec3(gamma)), fill.a);
+                        color = linear_fill * vec4(vec3(1.0), alpha);
+                    }
                 }
             "#,
 
             None
-        );
+        )?;
 
-        program.map(|program| SolidTextProgram { program })
+        Ok(SolidTextProgram { program, styled_program, msdf_program, icon_program })
     }
 
+    /// `pxrange` is the distance field spread baked into the atlas, in output pixels per
+    /// unit distance. It widens the anti-aliasing band so thin strokes do not disappear
+    /// when the text is minified, and narrows it again when magnified.
+    ///
+    /// `gamma` converts `fill` from sRGB to linear space before blending (`pow(fill, gamma)`)
+    /// so anti-aliased edges don't look too thin or too thick against a colored background.
+    /// Pass `DEFAULT_GAMMA` (2.2) to enable it, or `1.0` to draw `fill` unconverted.
     pub fn draw(
         &self,
         surface: &mut impl Surface,
         font_distance_field: &glium::texture::Texture2d,
         mesh: &TextMesh,
         fill: (f32, f32, f32, f32),
+        pxrange: f32,
+        gamma: f32,
         transform_matrix: [[f32; 4]; 4],
         draw_parameters: &DrawParameters,
     )
@@ -119,6 +411,117 @@ impl SolidTextProgram {
                 fill: fill,
                 transform: transform_matrix,
                 distance_field: font_distance_field,
+                pxrange: pxrange,
+                gamma: gamma,
+            },
+
+            draw_parameters
+        )
+    }
+
+    /// Like `draw`, but composites an outline, a soft glow, and a drop shadow underneath
+    /// the fill using the same distance field, with no extra geometry. See `draw` for
+    /// `gamma`.
+    pub fn draw_styled(
+        &self,
+        surface: &mut impl Surface,
+        font_distance_field: &glium::texture::Texture2d,
+        mesh: &TextMesh,
+        fill: (f32, f32, f32, f32),
+        pxrange: f32,
+        gamma: f32,
+        style: &TextStyle,
+        transform_matrix: [[f32; 4]; 4],
+        draw_parameters: &DrawParameters,
+    )
+        -> Result<(), DrawError>
+    {
+        surface.draw(
+            &mesh.vertices,
+            &mesh.indices,
+            &self.styled_program,
+
+            &uniform! {
+                fill: fill,
+                transform: transform_matrix,
+                distance_field: font_distance_field,
+                pxrange: pxrange,
+                gamma: gamma,
+                outline_color: style.outline_color,
+                outline_width: style.outline_width,
+                glow_color: style.glow_color,
+                glow_radius: style.glow_radius,
+                shadow_color: style.shadow_color,
+                shadow_offset: style.shadow_offset,
+                shadow_softness: style.shadow_softness,
+            },
+
+            draw_parameters
+        )
+    }
+
+    /// Like `draw`, but samples a 3-channel MSDF `font_distance_field` (see
+    /// `AtlasChannels::Msdf`) instead of a single-channel one, keeping sharp corners crisp
+    /// at high magnification. See `draw` for `gamma`.
+    pub fn draw_msdf(
+        &self,
+        surface: &mut impl Surface,
+        font_distance_field: &glium::texture::Texture2d,
+        mesh: &TextMesh,
+        fill: (f32, f32, f32, f32),
+        pxrange: f32,
+        gamma: f32,
+        transform_matrix: [[f32; 4]; 4],
+        draw_parameters: &DrawParameters,
+    )
+        -> Result<(), DrawError>
+    {
+        surface.draw(
+            &mesh.vertices,
+            &mesh.indices,
+            &self.msdf_program,
+
+            &uniform! {
+                fill: fill,
+                transform: transform_matrix,
+                distance_field: font_distance_field,
+                pxrange: pxrange,
+                gamma: gamma,
+            },
+
+            draw_parameters
+        )
+    }
+
+    /// Like `draw`, but for a `mesh` built with `TextMesh::new_with_custom`: each vertex
+    /// samples either `font_distance_field` or `icon_atlas` depending on whether it belongs
+    /// to a glyph or a `CustomGlyph`.
+    pub fn draw_with_icons(
+        &self,
+        surface: &mut impl Surface,
+        font_distance_field: &glium::texture::Texture2d,
+        icon_atlas: &glium::texture::Texture2d,
+        mesh: &TextMesh,
+        fill: (f32, f32, f32, f32),
+        pxrange: f32,
+        gamma: f32,
+        transform_matrix: [[f32; 4]; 4],
+        draw_parameters: &DrawParameters,
+    )
+        -> Result<(), DrawError>
+    {
+        surface.draw(
+            &mesh.vertices,
+            &mesh.indices,
+            &self.icon_program,
+
+            &uniform! {
+                fill: fill,
+                transform: transform_matrix,
+                distance_field: font_distance_field,
+                icon_atlas: icon_atlas,
+                pxrange: pxrange,
+                gamma: gamma,
             },
 
             draw_parameters
@@ -127,8 +530,138 @@ impl SolidTextProgram {
 }
 
 impl TextMesh {
-    pub fn new(facade: &impl Facade, font: &Font, text: &str) -> Result<Self, TextMeshCreationError> {
-        let (vertices, indices, width) = TextMesh::compute_buffers(font, text);
+    pub fn new(facade: &impl Facade, font: &Font, text: &str, layout: &LayoutOptions) -> Result<Self, TextMeshCreationError> {
+        let (vertices, indices, metrics) = TextMesh::compute_buffers(font, text, layout);
+
+        Ok(TextMesh {
+            vertices: glium::VertexBuffer::new(facade, &vertices)
+                .map_err(|e| TextMeshCreationError::Vertex(e))?,
+
+            indices: glium::IndexBuffer::new(facade, glium::index::PrimitiveType::TrianglesList, &indices)
+                .map_err(|e| TextMeshCreationError::Index(e))?,
+
+            metrics
+        })
+    }
+
+    pub fn set(&mut self, font: &Font, text: &str, layout: &LayoutOptions){
+        let (vertices, indices, metrics) = TextMesh::compute_buffers(font, text, layout);
+        self.vertices.write(&vertices);
+        self.indices.write(&indices);
+        self.metrics = metrics;
+    }
+
+    pub fn compute_buffers(font: &Font, text: &str, layout: &LayoutOptions) -> (Vec<GlyphQuadVertex>, Vec<u16>, TextMetrics) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+
+        let mut pen_y = 0.0;
+        let mut max_line_width = 0.0_f32;
+        let mut line_count = 0;
+
+        let paragraphs = text.replace("\r\n", "\n").replace('\r', "\n");
+
+        for paragraph in paragraphs.split('\n') {
+            for line in TextMesh::wrap_paragraph(font, paragraph, layout.max_width) {
+                let line_start = vertices.len();
+                let mut line_width = 0.0;
+
+                for glyph in font.layout_glyphs(line.chars()) {
+                    let quad_positions = glyph.layout.in_mesh.vertices();
+                    let quad_texture_coords = glyph.layout.in_atlas.vertices();
+
+                    for quad_vertex_index in 0..4 {
+                        for triangle_index in &[ 0,1,2,  2,3,0 ] {
+                            indices.push((vertices.len() + triangle_index) as u16);
+                        }
+
+                        line_width = glyph.layout.in_mesh.right();
+                        let (x, y) = quad_positions[quad_vertex_index];
+                        vertices.push(GlyphQuadVertex {
+                            position: (x, y + pen_y),
+                            texture_coordinate: quad_texture_coords[quad_vertex_index],
+                            is_custom: 0.0,
+                        });
+                    }
+                }
+
+                let align_offset = match layout.align {
+                    Align::Left => 0.0,
+                    Align::Center => -line_width / 2.0,
+                    Align::Right => -line_width,
+                };
+
+                if align_offset != 0.0 {
+                    for vertex in &mut vertices[line_start..] {
+                        vertex.position.0 += align_offset;
+                    }
+                }
+
+                max_line_width = max_line_width.max(line_width);
+                pen_y += layout.line_height;
+                line_count += 1;
+            }
+        }
+
+        let metrics = TextMetrics {
+            width: max_line_width,
+            height: line_count as f32 * layout.line_height,
+            line_count,
+        };
+
+        (vertices, indices, metrics)
+    }
+
+    /// Greedily splits `paragraph` (no `\n`/`\r`) into lines no wider than `max_width`,
+    /// breaking between words. A single word wider than `max_width` is not split and sits
+    /// alone on a line that exceeds it. Returns the paragraph unchanged if wrapping is disabled.
+    fn wrap_paragraph(font: &Font, paragraph: &str, max_width: Option<f32>) -> Vec<String> {
+        let max_width = match max_width {
+            Some(max_width) => max_width,
+            None => return vec![paragraph.to_string()],
+        };
+
+        let mut lines = Vec::new();
+        let mut current_line = String::new();
+
+        for word in paragraph.split(' ') {
+            let candidate = if current_line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", current_line, word)
+            };
+
+            if !current_line.is_empty() && TextMesh::measure_width(font, &candidate) > max_width {
+                lines.push(current_line);
+                current_line = word.to_string();
+            } else {
+                current_line = candidate;
+            }
+        }
+
+        lines.push(current_line);
+        lines
+    }
+
+    fn measure_width(font: &Font, text: &str) -> f32 {
+        font.layout_glyphs(text.chars())
+            .last()
+            .map(|glyph| glyph.layout.in_mesh.right())
+            .unwrap_or(0.0)
+    }
+
+    /// Like `new`, but sources glyph UVs from a `GlyphCache` instead of `font`'s static
+    /// `Atlas`, rasterizing any glyph in `text` that isn't already resident. Lets a mesh
+    /// render arbitrary/unbounded character sets from a bounded cache texture.
+    pub fn new_cached(
+        facade: &impl Facade,
+        font: &Font,
+        text: &str,
+        layout: &LayoutOptions,
+        cache: &mut GlyphCache,
+    ) -> Result<Self, TextMeshCreationError> {
+        let (vertices, indices, metrics) = TextMesh::compute_buffers_cached(font, text, layout, cache)
+            .map_err(TextMeshCreationError::Cache)?;
 
         Ok(TextMesh {
             vertices: glium::VertexBuffer::new(facade, &vertices)
@@ -137,40 +670,96 @@ impl TextMesh {
             indices: glium::IndexBuffer::new(facade, glium::index::PrimitiveType::TrianglesList, &indices)
                 .map_err(|e| TextMeshCreationError::Index(e))?,
 
-            width
+            metrics
         })
     }
 
-    pub fn set(&mut self, font: &Font, text: &str){
-        let (vertices, indices, width) = TextMesh::compute_buffers(font, text);
+    pub fn set_cached(&mut self, font: &Font, text: &str, layout: &LayoutOptions, cache: &mut GlyphCache) -> Result<(), GlyphCacheError> {
+        let (vertices, indices, metrics) = TextMesh::compute_buffers_cached(font, text, layout, cache)?;
         self.vertices.write(&vertices);
         self.indices.write(&indices);
-        self.width = width;
+        self.metrics = metrics;
+        Ok(())
     }
 
-    pub fn compute_buffers(font: &Font, text: &str) -> (Vec<GlyphQuadVertex>, Vec<u16>, f32) {
+    /// Same layout pass as `compute_buffers`, but each glyph's texture coordinates come
+    /// from `cache.uv_rect` rather than `glyph.layout.in_atlas`. Glyphs missing from the
+    /// cache are rasterized on demand before layout. Fails if a glyph cannot fit in the
+    /// cache even once evicted down to empty (see `GlyphCacheError::GlyphTooLarge`), or if
+    /// the glyphs of `text` do not all fit at once (see `GlyphCacheError::TooManyGlyphs`).
+    pub fn compute_buffers_cached(
+        font: &Font,
+        text: &str,
+        layout: &LayoutOptions,
+        cache: &mut GlyphCache,
+    ) -> Result<(Vec<GlyphQuadVertex>, Vec<u16>, TextMetrics), GlyphCacheError> {
+        cache.cache_glyphs(font, text)?;
+
         let mut vertices = Vec::new();
         let mut indices = Vec::new();
-        let mut width = 0.0;
 
-        for glyph in font.layout_glyphs(text.chars()) {
-            let quad_positions = glyph.layout.in_mesh.vertices();
-            let quad_texture_coords = glyph.layout.in_atlas.vertices();
+        let mut pen_y = 0.0;
+        let mut max_line_width = 0.0_f32;
+        let mut line_count = 0;
+
+        let paragraphs = text.replace("\r\n", "\n").replace('\r', "\n");
+
+        for paragraph in paragraphs.split('\n') {
+            for line in TextMesh::wrap_paragraph(font, paragraph, layout.max_width) {
+                let line_start = vertices.len();
+                let mut line_width = 0.0;
+
+                for glyph in font.layout_glyphs(line.chars()) {
+                    let quad_positions = glyph.layout.in_mesh.vertices();
+                    let (uv_min, uv_max) = cache.uv_rect(glyph.character).unwrap_or(((0.0, 0.0), (0.0, 0.0)));
+
+                    let quad_texture_coords = [
+                        (uv_min.0, uv_max.1),
+                        (uv_max.0, uv_max.1),
+                        (uv_max.0, uv_min.1),
+                        (uv_min.0, uv_min.1),
+                    ];
 
-            for quad_vertex_index in 0..4 {
-                for triangle_index in &[ 0,1,2,  2,3,0 ] {
-                    indices.push((vertices.len() + triangle_index) as u16);
+                    for quad_vertex_index in 0..4 {
+                        for triangle_index in &[ 0,1,2,  2,3,0 ] {
+                            indices.push((vertices.len() + triangle_index) as u16);
+                        }
+
+                        line_width = glyph.layout.in_mesh.right();
+                        let (x, y) = quad_positions[quad_vertex_index];
+                        vertices.push(GlyphQuadVertex {
+                            position: (x, y + pen_y),
+                            texture_coordinate: quad_texture_coords[quad_vertex_index],
+                            is_custom: 0.0,
+                        });
+                    }
+                }
+
+                let align_offset = match layout.align {
+                    Align::Left => 0.0,
+                    Align::Center => -line_width / 2.0,
+                    Align::Right => -line_width,
+                };
+
+                if align_offset != 0.0 {
+                    for vertex in &mut vertices[line_start..] {
+                        vertex.position.0 += align_offset;
+                    }
                 }
 
-                width = glyph.layout.in_mesh.right();
-                vertices.push(GlyphQuadVertex {
-                    position: quad_positions[quad_vertex_index],
-                    texture_coordinate: quad_texture_coords[quad_vertex_index]
-                });
+                max_line_width = max_line_width.max(line_width);
+                pen_y += layout.line_height;
+                line_count += 1;
             }
         }
 
-        (vertices, indices, width)
+        let metrics = TextMetrics {
+            width: max_line_width,
+            height: line_count as f32 * layout.line_height,
+            line_count,
+        };
+
+        Ok((vertices, indices, metrics))
     }
 
     pub fn vertices(&self) -> &glium::VertexBuffer<GlyphQuadVertex> {
@@ -182,7 +771,382 @@ impl TextMesh {
     }
 
     pub fn width(&self) -> f32 {
-        self.width
+        self.metrics.width
+    }
+
+    pub fn metrics(&self) -> TextMetrics {
+        self.metrics
+    }
+
+    /// Like `new`, but `spans` mixes ordinary text with `CustomGlyph`s that flow inline
+    /// (icons, emoji). `icon_uv` maps a `CustomGlyph::id` to its rectangle (min, max corner
+    /// in `0..1` texture space) within the icon atlas passed to `draw_with_icons`.
+    /// Lines break only on `\n`/`\r`: `layout.max_width` is ignored and nothing is wrapped.
+    pub fn new_with_custom(
+        facade: &impl Facade,
+        font: &Font,
+        spans: &[TextSpan],
+        layout: &LayoutOptions,
+        icon_uv: impl Fn(u32) -> ((f32, f32), (f32, f32)),
+    ) -> Result<Self, TextMeshCreationError> {
+        let (vertices, indices, metrics) = TextMesh::compute_buffers_with_custom(font, spans, layout, icon_uv);
+
+        Ok(TextMesh {
+            vertices: glium::VertexBuffer::new(facade, &vertices)
+                .map_err(|e| TextMeshCreationError::Vertex(e))?,
+
+            indices: glium::IndexBuffer::new(facade, glium::index::PrimitiveType::TrianglesList, &indices)
+                .map_err(|e| TextMeshCreationError::Index(e))?,
+
+            metrics
+        })
+    }
+
+    pub fn set_with_custom(
+        &mut self,
+        font: &Font,
+        spans: &[TextSpan],
+        layout: &LayoutOptions,
+        icon_uv: impl Fn(u32) -> ((f32, f32), (f32, f32)),
+    ) {
+        let (vertices, indices, metrics) = TextMesh::compute_buffers_with_custom(font, spans, layout, icon_uv);
+        self.vertices.write(&vertices);
+        self.indices.write(&indices);
+        self.metrics = metrics;
+    }
+
+    pub fn compute_buffers_with_custom(
+        font: &Font,
+        spans: &[TextSpan],
+        layout: &LayoutOptions,
+        icon_uv: impl Fn(u32) -> ((f32, f32), (f32, f32)),
+    ) -> (Vec<GlyphQuadVertex>, Vec<u16>, TextMetrics) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+
+        let mut pen_x = 0.0_f32;
+        let mut pen_y = 0.0_f32;
+        let mut line_width = 0.0_f32;
+        let mut max_line_width = 0.0_f32;
+        let mut line_count = 1;
+        let mut line_start = 0usize;
+
+        for span in spans {
+            match span {
+                TextSpan::Text(text) => {
+                    // Lay out each newline-delimited run as a whole, like `compute_buffers`,
+                    // so `font.layout_glyphs` accounts for kerning, left-side bearings, and
+                    // space advances — laying out one character at a time loses all three.
+                    let normalized = text.replace("\r\n", "\n").replace('\r', "\n");
+                    let mut runs = normalized.split('\n').peekable();
+
+                    while let Some(run) = runs.next() {
+                        if !run.is_empty() {
+                            let mut run_width = 0.0_f32;
+
+                            for glyph in font.layout_glyphs(run.chars()) {
+                                let quad_positions = glyph.layout.in_mesh.vertices();
+                                let quad_texture_coords = glyph.layout.in_atlas.vertices();
+
+                                let positioned: Vec<(f32, f32)> = quad_positions.iter()
+                                    .map(|&(x, y)| (x + pen_x, y + pen_y))
+                                    .collect();
+
+                                TextMesh::push_quad(&mut vertices, &mut indices, &positioned, &quad_texture_coords, 0.0);
+
+                                run_width = glyph.layout.in_mesh.right();
+                            }
+
+                            pen_x += run_width;
+                            line_width = pen_x;
+                        }
+
+                        if runs.peek().is_some() {
+                            TextMesh::finish_line(&mut vertices, line_start, line_width, layout.align);
+                            max_line_width = max_line_width.max(line_width);
+                            pen_x = 0.0;
+                            pen_y += layout.line_height;
+                            line_width = 0.0;
+                            line_start = vertices.len();
+                            line_count += 1;
+                        }
+                    }
+                }
+
+                TextSpan::Custom(glyph) => {
+                    let (uv_min, uv_max) = icon_uv(glyph.id);
+                    let bottom = pen_y + glyph.baseline_offset;
+                    let top = bottom + glyph.height;
+
+                    let positions = [
+                        (pen_x, bottom),
+                        (pen_x + glyph.width, bottom),
+                        (pen_x + glyph.width, top),
+                        (pen_x, top),
+                    ];
+
+                    let texture_coords = [
+                        (uv_min.0, uv_max.1),
+                        (uv_max.0, uv_max.1),
+                        (uv_max.0, uv_min.1),
+                        (uv_min.0, uv_min.1),
+                    ];
+
+                    TextMesh::push_quad(&mut vertices, &mut indices, &positions, &texture_coords, 1.0);
+
+                    pen_x += glyph.width;
+                    line_width = pen_x;
+                }
+            }
+        }
+
+        TextMesh::finish_line(&mut vertices, line_start, line_width, layout.align);
+        max_line_width = max_line_width.max(line_width);
+
+        let metrics = TextMetrics {
+            width: max_line_width,
+            height: line_count as f32 * layout.line_height,
+            line_count,
+        };
+
+        (vertices, indices, metrics)
+    }
+
+    fn push_quad(
+        vertices: &mut Vec<GlyphQuadVertex>,
+        indices: &mut Vec<u16>,
+        positions: &[(f32, f32)],
+        texture_coords: &[(f32, f32)],
+        is_custom: f32,
+    ) {
+        for quad_vertex_index in 0..4 {
+            for triangle_index in &[ 0,1,2,  2,3,0 ] {
+                indices.push((vertices.len() + triangle_index) as u16);
+            }
+
+            vertices.push(GlyphQuadVertex {
+                position: positions[quad_vertex_index],
+                texture_coordinate: texture_coords[quad_vertex_index],
+                is_custom,
+            });
+        }
+    }
+
+    /// Shifts every vertex from `line_start` onward horizontally so the line reads as
+    /// left/center/right-aligned once its width is known.
+    fn finish_line(vertices: &mut [GlyphQuadVertex], line_start: usize, line_width: f32, align: Align) {
+        let align_offset = match align {
+            Align::Left => 0.0,
+            Align::Center => -line_width / 2.0,
+            Align::Right => -line_width,
+        };
+
+        if align_offset != 0.0 {
+            for vertex in &mut vertices[line_start..] {
+                vertex.position.0 += align_offset;
+            }
+        }
+    }
+}
+
+/// A shelf (row) of glyphs packed left-to-right into the cache texture, all sharing the
+/// tallest glyph height seen on that row.
+struct Shelf {
+    y: u32,
+    height: u32,
+    cursor_x: u32,
+}
+
+#[derive(Copy, Clone)]
+struct CachedGlyph {
+    rect: Rect,
+}
+
+#[derive(Debug)]
+pub enum GlyphCacheError {
+    /// The glyph's distance field does not fit even in an otherwise-empty cache texture.
+    GlyphTooLarge,
+    /// The glyphs of one `cache_glyphs` call do not all fit in the cache texture at once.
+    TooManyGlyphs,
+    Texture(TextureCreationError),
+}
+
+/// A GPU-resident, bounded-size distance field texture that rasterizes glyphs on demand,
+/// modeled on rusttype's dynamic `gpu_cache`. Where `atlas_texture` requires every glyph a
+/// `Font` might ever draw to be pre-baked up front, `GlyphCache` only uploads the glyphs a
+/// caller actually asks for, evicting the least-recently-used ones to make room for new
+/// ones once the texture fills. This lets `TextMesh` render unbounded character sets (CJK,
+/// arbitrary user input) from a single fixed-size texture.
+pub struct GlyphCache {
+    texture: Texture2d,
+    resolution: (u32, u32),
+    shelves: Vec<Shelf>,
+    glyphs: HashMap<char, CachedGlyph>,
+    /// Least-recently-used order: the front is evicted first.
+    recency: VecDeque<char>,
+}
+
+impl GlyphCache {
+    pub fn new(facade: &impl Facade, resolution: (u32, u32)) -> Result<Self, GlyphCacheError> {
+        let texture = Texture2d::empty(facade, resolution.0, resolution.1)
+            .map_err(GlyphCacheError::Texture)?;
+
+        Ok(GlyphCache {
+            texture,
+            resolution,
+            shelves: Vec::new(),
+            glyphs: HashMap::new(),
+            recency: VecDeque::new(),
+        })
+    }
+
+    pub fn texture(&self) -> &Texture2d {
+        &self.texture
+    }
+
+    /// Ensures the distance field for every character of `text` is resident in the cache,
+    /// rasterizing and uploading whatever is missing. Returns an error only if a single
+    /// glyph cannot fit even in a freshly-evicted, otherwise-empty cache, or if the glyphs
+    /// of `text` cannot all be resident at once.
+    pub fn cache_glyphs(&mut self, font: &Font, text: &str) -> Result<(), GlyphCacheError> {
+        for character in text.chars() {
+            self.cache_glyph(font, character)?;
+        }
+
+        // Making room for a later glyph can evict one cached earlier in this same call.
+        let evicted = text.chars().any(|character| {
+            !self.glyphs.contains_key(&character) && font.rasterize_distance_field(character).is_some()
+        });
+
+        if evicted {
+            return Err(GlyphCacheError::TooManyGlyphs);
+        }
+
+        Ok(())
+    }
+
+    /// The UV rectangle (min, max corners in `0..1` texture space) of a previously cached
+    /// glyph, or `None` if it has not been cached (see `cache_glyphs`).
+    pub fn uv_rect(&self, character: char) -> Option<((f32, f32), (f32, f32))> {
+        self.glyphs.get(&character).map(|glyph| {
+            let (width, height) = (self.resolution.0 as f32, self.resolution.1 as f32);
+
+            (
+                (glyph.rect.left as f32 / width, glyph.rect.bottom as f32 / height),
+                (
+                    (glyph.rect.left + glyph.rect.width) as f32 / width,
+                    (glyph.rect.bottom + glyph.rect.height) as f32 / height,
+                ),
+            )
+        })
+    }
+
+    /// Rasterizes and uploads `character` if it isn't already cached. Does nothing for a
+    /// character `font` can't rasterize (whitespace, control characters) — that's not a
+    /// cache failure, just a glyph with no ink to upload.
+    fn cache_glyph(&mut self, font: &Font, character: char) -> Result<(), GlyphCacheError> {
+        if self.glyphs.contains_key(&character) {
+            self.touch(character);
+            return Ok(());
+        }
+
+        let (pixels, size) = match font.rasterize_distance_field(character) {
+            Some(rasterized) => rasterized,
+            None => return Ok(()),
+        };
+
+        let rect = self.allocate(size).or_else(|| self.evict_until_fits(font, size))
+            .ok_or(GlyphCacheError::GlyphTooLarge)?;
+
+        self.texture.main_level().write(rect, RawImage2d {
+            data: Cow::Owned(pixels),
+            width: size.0,
+            height: size.1,
+            format: ClientFormat::U8,
+        });
+
+        self.glyphs.insert(character, CachedGlyph { rect });
+        self.recency.push_back(character);
+
+        Ok(())
+    }
+
+    fn touch(&mut self, character: char) {
+        if let Some(position) = self.recency.iter().position(|&cached| cached == character) {
+            self.recency.remove(position);
+            self.recency.push_back(character);
+        }
+    }
+
+    /// Finds room for a `size`-pixel glyph on an existing shelf, or opens a new shelf if
+    /// there is unused height left at the bottom of the texture.
+    fn allocate(&mut self, size: (u32, u32)) -> Option<Rect> {
+        let (width, height) = size;
+
+        if width > self.resolution.0 || height > self.resolution.1 {
+            return None;
+        }
+
+        for shelf in &mut self.shelves {
+            if shelf.height >= height && self.resolution.0 - shelf.cursor_x >= width {
+                let rect = Rect { left: shelf.cursor_x, bottom: shelf.y, width, height };
+                shelf.cursor_x += width;
+                return Some(rect);
+            }
+        }
+
+        let next_y = self.shelves.iter().map(|shelf| shelf.y + shelf.height).max().unwrap_or(0);
+        if self.resolution.1 - next_y < height {
+            return None;
+        }
+
+        self.shelves.push(Shelf { y: next_y, height, cursor_x: width });
+        Some(Rect { left: 0, bottom: next_y, width, height })
+    }
+
+    /// Evicts the least-recently-used glyphs one at a time until `size` fits, then repacks
+    /// from scratch (shelf allocators cannot reclaim a single freed glyph's space without a
+    /// full repack) — re-rasterizing and re-uploading every surviving glyph to its new
+    /// `Rect`, since the cache only keeps the pixels of a glyph resident in the texture
+    /// itself. A surviving glyph that no longer fits after repacking is evicted as well.
+    /// Returns `None` if even an empty cache can't fit `size`.
+    fn evict_until_fits(&mut self, font: &Font, size: (u32, u32)) -> Option<Rect> {
+        while let Some(oldest) = self.recency.pop_front() {
+            self.glyphs.remove(&oldest);
+            self.shelves.clear();
+
+            let surviving: Vec<char> = self.recency.iter().copied().collect();
+            let mut repacked = HashMap::with_capacity(surviving.len());
+
+            for character in surviving {
+                let (pixels, glyph_size) = match font.rasterize_distance_field(character) {
+                    Some(rasterized) => rasterized,
+                    None => continue,
+                };
+
+                if let Some(rect) = self.allocate(glyph_size) {
+                    self.texture.main_level().write(rect, RawImage2d {
+                        data: Cow::Owned(pixels),
+                        width: glyph_size.0,
+                        height: glyph_size.1,
+                        format: ClientFormat::U8,
+                    });
+
+                    repacked.insert(character, CachedGlyph { rect });
+                }
+            }
+
+            self.glyphs = repacked;
+
+            let glyphs = &self.glyphs;
+            self.recency.retain(|character| glyphs.contains_key(character));
+
+            if let Some(rect) = self.allocate(size) {
+                return Some(rect);
+            }
+        }
+
+        None
     }
 }
 
@@ -202,7 +1166,8 @@ mod test {
 
         let font = Font::deserialized(font);
         let font_texture = crate::glium_render::atlas_texture(&display, &font.atlas).unwrap();
-        let text_mesh = crate::glium_render::TextMesh::new(&display, &font, "Hello World").unwrap();
+        let layout = crate::glium_render::LayoutOptions::default();
+        let text_mesh = crate::glium_render::TextMesh::new(&display, &font, "Hello World", &layout).unwrap();
         let solid_text_program = crate::glium_render::SolidTextProgram::new(&display).unwrap();
 
         let mut closed = false;
@@ -223,7 +1188,11 @@ mod test {
                     ..Default::default()
                 };
 
-                solid_text_program.draw(&mut target, &font_texture, &text_mesh, fill, transform, &draw_parameters).unwrap();
+                solid_text_program.draw(
+                    &mut target, &font_texture, &text_mesh, fill,
+                    font.atlas.spread, crate::glium_render::DEFAULT_GAMMA,
+                    transform, &draw_parameters
+                ).unwrap();
             }
 
             target.finish().unwrap();